@@ -1,17 +1,193 @@
 use nalgebra::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
 use wasm_bindgen::prelude::*;
 
 /// Local flock range determines how close another boid must be before the current boid conciders
 /// it while setting course.
 pub const LOCAL_RANGE: f32 = 100.0;
-pub const LOCAL_RANGE_SQ: f32 = LOCAL_RANGE * LOCAL_RANGE;
 
+/// Lower bound on cruising speed; boids below this are rescaled back up along their heading so
+/// they don't stall to near-zero when accelerations cancel out.
+const MIN_SPEED: f32 = 50.0;
 const MAX_SPEED: f32 = 400.0;
 const MAX_ACC: f32 = 600.0;
 /// Margin to avoid obsticles
 const MARGIN: f32 = 100.0;
-/// Acceleration away from obsticles
-const OBS_ACC: f32 = MAX_ACC;
+/// Default blend radius for the obstacle signed-distance field smooth-union
+const DEFAULT_OBSTACLE_BLEND: f32 = 50.0;
+/// Default cap on how many of the closest neighbors influence a boid
+const DEFAULT_MAX_NEIGHBORS: u32 = 7;
+/// Default view cosine threshold; -1.0 admits every direction (a full circle)
+const DEFAULT_VIEW_COS: f32 = -1.0;
+/// Default radius within which a predator triggers a boid's flee response
+const DEFAULT_DANGER_RADIUS: f32 = 150.0;
+/// Default standard deviation (in world units) of a predator's noisy observation of the flock
+/// center, used as the particle filter's likelihood spread
+const DEFAULT_PREDATOR_NOISE: f32 = 40.0;
+/// Number of particles each predator's filter carries
+const PARTICLE_COUNT: usize = 30;
+/// Per-step random-walk distance used to predict particles forward before reweighting
+const PARTICLE_STEP: f32 = 6.0;
+const PREDATOR_MAX_SPEED: f32 = 450.0;
+const PREDATOR_MAX_ACC: f32 = 900.0;
+
+/// Tunable weights and limits for `Flock::update`, held by `Flock` and threaded through every
+/// `Boid::update` call so a host can reshape flock behavior without recompiling.
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub struct SimParams {
+    separation_weight: f32,
+    cohesion_weight: f32,
+    alignment_weight: f32,
+    local_range: f32,
+    min_speed: f32,
+    max_speed: f32,
+    target_speed: f32,
+    max_acc: f32,
+    margin: f32,
+    /// Cap on how many of the closest in-range neighbors are allowed to influence a boid
+    max_neighbors: u32,
+    /// Cosine of the forward field-of-view half-angle; a neighbor behind this threshold is ignored
+    view_cos_threshold: f32,
+    /// Weight applied to the combined attractor/repeller seek steering
+    target_weight: f32,
+    /// Radius within which a predator triggers a boid's flee response
+    danger_radius: f32,
+    /// Weight applied to the predator flee steering
+    flee_weight: f32,
+}
+
+#[wasm_bindgen]
+impl SimParams {
+    pub fn new() -> SimParams {
+        SimParams {
+            separation_weight: 6.0,
+            cohesion_weight: 1.0,
+            alignment_weight: 1.0,
+            local_range: LOCAL_RANGE,
+            min_speed: MIN_SPEED,
+            max_speed: MAX_SPEED,
+            target_speed: MAX_SPEED,
+            max_acc: MAX_ACC,
+            margin: MARGIN,
+            max_neighbors: DEFAULT_MAX_NEIGHBORS,
+            view_cos_threshold: DEFAULT_VIEW_COS,
+            target_weight: 1.0,
+            danger_radius: DEFAULT_DANGER_RADIUS,
+            flee_weight: 8.0,
+        }
+    }
+
+    pub fn set_separation_weight(&mut self, weight: f32) {
+        self.separation_weight = weight;
+    }
+
+    pub fn set_cohesion_weight(&mut self, weight: f32) {
+        self.cohesion_weight = weight;
+    }
+
+    pub fn set_alignment_weight(&mut self, weight: f32) {
+        self.alignment_weight = weight;
+    }
+
+    pub fn set_local_range(&mut self, range: f32) {
+        self.local_range = range;
+    }
+
+    pub fn set_min_speed(&mut self, speed: f32) {
+        self.min_speed = speed;
+    }
+
+    pub fn set_max_speed(&mut self, speed: f32) {
+        self.max_speed = speed;
+    }
+
+    pub fn set_target_speed(&mut self, speed: f32) {
+        self.target_speed = speed;
+    }
+
+    pub fn set_max_acc(&mut self, acc: f32) {
+        self.max_acc = acc;
+    }
+
+    pub fn set_margin(&mut self, margin: f32) {
+        self.margin = margin;
+    }
+
+    pub fn set_max_neighbors(&mut self, max_neighbors: u32) {
+        self.max_neighbors = max_neighbors;
+    }
+
+    /// Sets the forward field of view as a half-angle in radians; neighbors outside it are
+    /// ignored. `PI` (or greater) admits every direction.
+    pub fn set_view_angle(&mut self, half_angle_radians: f32) {
+        self.view_cos_threshold = half_angle_radians.min(std::f32::consts::PI).cos();
+    }
+
+    pub fn set_target_weight(&mut self, weight: f32) {
+        self.target_weight = weight;
+    }
+
+    pub fn set_danger_radius(&mut self, radius: f32) {
+        self.danger_radius = radius;
+    }
+
+    pub fn set_flee_weight(&mut self, weight: f32) {
+        self.flee_weight = weight;
+    }
+}
+
+impl Default for SimParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A static obstacle for the flock to avoid. Not `#[wasm_bindgen]`-exported directly since
+/// wasm-bindgen can't carry data-bearing enums across the boundary; the host builds obstacles
+/// through `Flock::add_circle_obstacle` / `Flock::add_box_obstacle` instead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Obstacle {
+    Circle { center: Point2<f32>, radius: f32 },
+    AabbBox { min: Point2<f32>, max: Point2<f32> },
+}
+
+impl Obstacle {
+    /// Signed distance from `p` to this obstacle's surface; negative when `p` is inside.
+    fn sdf(&self, p: Point2<f32>) -> f32 {
+        match self {
+            Obstacle::Circle { center, radius } => (p - center).magnitude() - radius,
+            Obstacle::AabbBox { min, max } => {
+                let dx = (min.x - p.x).max(p.x - max.x);
+                let dy = (min.y - p.y).max(p.y - max.y);
+                let outside = Vector2::new(dx.max(0.0), dy.max(0.0)).magnitude();
+                let inside = dx.max(dy).min(0.0);
+                outside + inside
+            }
+        }
+    }
+}
+
+/// Polynomial smooth-min of two signed distances, blending over a radius `k` so nearby or
+/// overlapping obstacles merge into one surface instead of producing conflicting push vectors.
+fn smin(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b * (1.0 - h) + a * h - k * h * (1.0 - h)
+}
+
+/// Combined signed-distance field of every obstacle, smooth-unioned with blend radius `k`.
+fn obstacle_field(obstacles: &[Obstacle], p: Point2<f32>, k: f32) -> f32 {
+    let mut field = f32::INFINITY;
+    for obstacle in obstacles {
+        let d = obstacle.sdf(p);
+        field = if field.is_infinite() { d } else { smin(field, d, k) };
+    }
+    field
+}
 
 #[wasm_bindgen]
 #[derive(Clone)]
@@ -29,50 +205,84 @@ impl Boid {
         }
     }
 
-    pub fn is_close(&self, other: &Boid) -> bool {
+    pub fn is_close(&self, other: &Boid, range_sq: f32) -> bool {
         let dist_sq = (self.position - other.position).magnitude_squared();
-        dist_sq < LOCAL_RANGE_SQ
+        dist_sq < range_sq
     }
 
-    /// Update the boid based on current neighbors
-    pub fn update(&mut self, neighbors: &Vec<Boid>, dt: f32, bounds: Vector2<f32>, obstacle: Option<Point2<f32>>) {
-        let avoid_boids = self.avoid(&neighbors);
-        let to_center = self.to_center(&neighbors);
-        let match_heading = self.match_heading(&neighbors);
-        let acc_to_target_speed = self.acc_to_target_speed();
-        let avoid_bounds = self.avoid_bounds(bounds);
-        let avoid_obstacle = self.avoid_obstacle(obstacle);
+    /// Update the boid based on current neighbors.
+    /// `members` is the full flock and `neighbor_indices` selects which of them are close enough
+    /// to this boid to influence it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        members: &[Boid],
+        neighbor_indices: &[usize],
+        dt: f32,
+        bounds: Vector2<f32>,
+        obstacles: &[Obstacle],
+        obstacle_blend: f32,
+        attractors: &[(Point2<f32>, f32)],
+        repellers: &[(Point2<f32>, f32)],
+        predators: &[Predator],
+        params: &SimParams,
+    ) {
+        let avoid_boids = self.avoid(members, neighbor_indices, params);
+        let to_center = self.to_center(members, neighbor_indices);
+        let match_heading = self.match_heading(members, neighbor_indices);
+        let acc_to_target_speed = self.acc_to_target_speed(params);
+        let avoid_bounds = self.avoid_bounds(bounds, params);
+        let avoid_obstacle = self.avoid_obstacles(obstacles, obstacle_blend, params);
+        let seek_targets = self.seek_targets(attractors, repellers);
+        let flee_predators = self.flee_predators(predators, params.danger_radius);
 
         let acc = 10.0 *
-            (6.0 * avoid_boids
-             + to_center
-             + match_heading
+            (params.separation_weight * avoid_boids
+             + params.cohesion_weight * to_center
+             + params.alignment_weight * match_heading
              + acc_to_target_speed
              + avoid_bounds
              + avoid_obstacle
+             + params.target_weight * seek_targets
+             + params.flee_weight * flee_predators
             );
-        self.velocity += clamp_mag(acc, MAX_ACC) * dt;
-        self.velocity = clamp_mag(self.velocity, MAX_SPEED);
+        self.velocity += clamp_mag(acc, params.max_acc) * dt;
+        self.velocity = clamp_mag(self.velocity, params.max_speed);
+        self.enforce_min_speed(params);
     }
 
-    fn acc_to_target_speed(&self) -> Vector2<f32> {
+    fn acc_to_target_speed(&self, params: &SimParams) -> Vector2<f32> {
         let v_mag = self.velocity.magnitude();
         if v_mag > 0.001 {
-            let diff = MAX_SPEED - v_mag;
+            let diff = params.target_speed - v_mag;
             self.velocity * diff / v_mag
         } else {
             Vector2::new(1.0, 0.0)
         }
     }
 
+    /// Rescales the velocity up to `params.min_speed` along its current heading if it has fallen
+    /// below that, picking a default heading when the boid is ~stopped.
+    fn enforce_min_speed(&mut self, params: &SimParams) {
+        let v_mag = self.velocity.magnitude();
+        if v_mag < params.min_speed {
+            let heading = if v_mag > 0.001 {
+                self.velocity / v_mag
+            } else {
+                Vector2::new(1.0, 0.0)
+            };
+            self.velocity = heading * params.min_speed;
+        }
+    }
+
     /// Vector to avoid nearby boids
-    fn avoid(&self, group: &Vec<Boid>) -> Vector2<f32> {
+    fn avoid(&self, members: &[Boid], neighbor_indices: &[usize], params: &SimParams) -> Vector2<f32> {
         let mut total = Vector2::zeros();
-        for boid in group {
-            total += self.avoid_point(boid.position, LOCAL_RANGE);
+        for &i in neighbor_indices {
+            total += self.avoid_point(members[i].position, params.local_range);
         }
-        if group.len() > 0 {
-            total / (group.len() as f32)
+        if !neighbor_indices.is_empty() {
+            total / (neighbor_indices.len() as f32)
         } else {
             Vector2::zeros()
         }
@@ -93,81 +303,440 @@ impl Boid {
         }
     }
 
-    fn avoid_obstacle(&self, obstacle: Option<Point2<f32>>) -> Vector2<f32> {
-        match obstacle {
-            Some(point) => self.avoid_point(point, 300.0),
-            None => Vector2::zeros(),
+    /// Vector to avoid every obstacle, following `grad(field)` of their combined signed-distance
+    /// field (the direction distance increases fastest) so overlapping obstacles blend into one
+    /// smooth barrier instead of fighting each other. The gradient is estimated by central
+    /// differences and scaled up as the boid nears the zero isosurface, inside `params.margin`.
+    fn avoid_obstacles(&self, obstacles: &[Obstacle], blend: f32, params: &SimParams) -> Vector2<f32> {
+        if obstacles.is_empty() {
+            return Vector2::zeros();
+        }
+
+        let field = obstacle_field(obstacles, self.position, blend);
+        if field > params.margin {
+            return Vector2::zeros();
         }
+
+        const EPS: f32 = 1.0;
+        let dx = obstacle_field(obstacles, self.position + Vector2::new(EPS, 0.0), blend)
+            - obstacle_field(obstacles, self.position - Vector2::new(EPS, 0.0), blend);
+        let dy = obstacle_field(obstacles, self.position + Vector2::new(0.0, EPS), blend)
+            - obstacle_field(obstacles, self.position - Vector2::new(0.0, EPS), blend);
+        let gradient = Vector2::new(dx, dy) / (2.0 * EPS);
+        let gradient_mag = gradient.magnitude();
+        if gradient_mag < 0.001 {
+            return Vector2::zeros();
+        }
+
+        let push_mag = params.margin - field;
+        gradient / gradient_mag * push_mag
     }
 
-    fn avoid_bounds(&self, bounds: Vector2<f32>) -> Vector2<f32> {
+    fn avoid_bounds(&self, bounds: Vector2<f32>, params: &SimParams) -> Vector2<f32> {
         let mut total = Vector2::zeros();
+        let margin = params.margin;
+        let obs_acc = params.max_acc;
 
-        if self.position.x < MARGIN {
-            total += Vector2::new(OBS_ACC, 0.0);
+        if self.position.x < margin {
+            total += Vector2::new(obs_acc, 0.0);
         }
-        if self.position.y < MARGIN {
-            total += Vector2::new(0.0, OBS_ACC);
+        if self.position.y < margin {
+            total += Vector2::new(0.0, obs_acc);
         }
-        if self.position.x > bounds.x - MARGIN {
-            total += Vector2::new(-OBS_ACC, 0.0);
+        if self.position.x > bounds.x - margin {
+            total += Vector2::new(-obs_acc, 0.0);
         }
-        if self.position.y > bounds.y - MARGIN {
-            total += Vector2::new(0.0, -OBS_ACC);
+        if self.position.y > bounds.y - margin {
+            total += Vector2::new(0.0, -obs_acc);
         }
 
         total
     }
 
     /// Vector to go to the center of mass of the neighbors
-    fn to_center(&self, group: &Vec<Boid>) -> Vector2<f32> {
-        let com = center_of_mass(group).unwrap_or(self.position);
+    fn to_center(&self, members: &[Boid], neighbor_indices: &[usize]) -> Vector2<f32> {
+        let com = center_of_mass(members, neighbor_indices).unwrap_or(self.position);
         com - self.position
     }
 
-    fn match_heading(&self, group: &Vec<Boid>) -> Vector2<f32> {
-        if let Some(group_heading) = average_heading(group) {
+    fn match_heading(&self, members: &[Boid], neighbor_indices: &[usize]) -> Vector2<f32> {
+        if let Some(group_heading) = average_heading(members, neighbor_indices) {
             group_heading - self.velocity
         } else {
             Vector2::zeros()
         }
     }
 
+    /// Normalized steering vector toward `point`, scaled by `weight`. Negate the result (or pass
+    /// a negative `weight`) to flee the point instead of seeking it.
+    pub fn seek(&self, point: Point2<f32>, weight: f32) -> Vector2<f32> {
+        let diff = point - self.position;
+        let diff_mag = diff.magnitude();
+        if diff_mag > 0.001 {
+            diff / diff_mag * weight
+        } else {
+            Vector2::zeros()
+        }
+    }
+
+    /// Combined steering from every attractor (seeking) and repeller (fleeing)
+    fn seek_targets(&self, attractors: &[(Point2<f32>, f32)], repellers: &[(Point2<f32>, f32)]) -> Vector2<f32> {
+        let mut total = Vector2::zeros();
+        for &(point, weight) in attractors {
+            total += self.seek(point, weight);
+        }
+        for &(point, weight) in repellers {
+            total -= self.seek(point, weight);
+        }
+        total
+    }
+
+    /// Strong steering away from every predator within `danger_radius`, scaling inversely with
+    /// distance so an imminent threat dominates the boid's other behaviors.
+    fn flee_predators(&self, predators: &[Predator], danger_radius: f32) -> Vector2<f32> {
+        let mut total = Vector2::zeros();
+        for predator in predators {
+            let diff = self.position - predator.position;
+            let dist = diff.magnitude();
+            if dist > 0.001 && dist < danger_radius {
+                total += diff / dist * (danger_radius - dist) / dist;
+            }
+        }
+        total
+    }
+
 }
 
-fn center_of_mass(group: &Vec<Boid>) -> Option<Point2<f32>> {
-    if group.len() == 0 {
+fn center_of_mass(members: &[Boid], neighbor_indices: &[usize]) -> Option<Point2<f32>> {
+    if neighbor_indices.is_empty() {
         return None;
     }
     let mut total = Vector2::zeros();
-    let num = group.len() as f32;
-    for boid in group {
-        total += boid.position.coords;
+    let num = neighbor_indices.len() as f32;
+    for &i in neighbor_indices {
+        total += members[i].position.coords;
     }
     Some(Point2::from(total / num))
 }
 
-fn average_heading(group: &Vec<Boid>) -> Option<Vector2<f32>> {
-    if group.len() > 0 {
+/// True center of mass of the whole flock, used as the ground truth a predator's observation is
+/// a noisy reading of.
+fn flock_center(members: &[Boid]) -> Option<Point2<f32>> {
+    if members.is_empty() {
+        return None;
+    }
+    let mut total = Vector2::zeros();
+    for member in members {
+        total += member.position.coords;
+    }
+    Some(Point2::from(total / members.len() as f32))
+}
+
+fn average_heading(members: &[Boid], neighbor_indices: &[usize]) -> Option<Vector2<f32>> {
+    if !neighbor_indices.is_empty() {
         let mut total = Vector2::zeros();
-        for boid in group {
-            total += boid.velocity;
+        for &i in neighbor_indices {
+            total += members[i].velocity;
         }
-        Some(total / group.len() as f32)
+        Some(total / neighbor_indices.len() as f32)
     } else {
         None
     }
 }
 
+/// Minimal xorshift32 PRNG, used to drive the particle filter without pulling in a dependency.
+struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    fn new(seed: u32) -> Rng {
+        Rng { state: if seed == 0 { 0x9E37_79B9 } else { seed } }
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Uniform float in `[-1.0, 1.0]`
+    fn next_signed(&mut self) -> f32 {
+        (self.next_u32() as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+
+    /// Uniform float in `[0.0, max]`
+    fn next_scaled(&mut self, max: f32) -> f32 {
+        (self.next_u32() as f32 / u32::MAX as f32) * max
+    }
+}
+
+/// Tracks a predator's belief about the flock's center of mass from noisy observations alone.
+/// Each step predicts every particle forward with a small random walk, reweights by the Gaussian
+/// likelihood of the latest noisy observation, then resamples proportionally to weight so
+/// particles that drifted away from the true center die out.
+struct ParticleFilter {
+    particles: Vec<Point2<f32>>,
+    weights: Vec<f32>,
+}
+
+impl ParticleFilter {
+    fn new(initial_guess: Point2<f32>) -> ParticleFilter {
+        ParticleFilter {
+            particles: vec![initial_guess; PARTICLE_COUNT],
+            weights: vec![1.0 / PARTICLE_COUNT as f32; PARTICLE_COUNT],
+        }
+    }
+
+    /// Predicts, reweights by `observation`'s likelihood, then resamples. Returns the
+    /// weighted-mean estimate computed from the reweighted particles, before resampling resets
+    /// every weight back to uniform.
+    fn update(&mut self, observation: Point2<f32>, noise: f32, rng: &mut Rng) -> Point2<f32> {
+        for particle in self.particles.iter_mut() {
+            *particle += Vector2::new(rng.next_signed(), rng.next_signed()) * PARTICLE_STEP;
+        }
+
+        let variance = (noise * noise).max(0.001);
+        let mut total_weight = 0.0;
+        for (particle, weight) in self.particles.iter().zip(self.weights.iter_mut()) {
+            let dist_sq = (particle - observation).magnitude_squared();
+            *weight = (-0.5 * dist_sq / variance).exp();
+            total_weight += *weight;
+        }
+
+        if total_weight > 0.001 {
+            for weight in self.weights.iter_mut() {
+                *weight /= total_weight;
+            }
+        } else {
+            let uniform = 1.0 / self.weights.len() as f32;
+            for weight in self.weights.iter_mut() {
+                *weight = uniform;
+            }
+        }
+
+        let estimate = self.estimate();
+        self.resample(rng);
+        estimate
+    }
+
+    /// Resamples particles proportionally to their weight, replacing degenerate ones, then resets
+    /// every weight back to uniform.
+    fn resample(&mut self, rng: &mut Rng) {
+        let mut cumulative = Vec::with_capacity(self.weights.len());
+        let mut running = 0.0;
+        for &weight in &self.weights {
+            running += weight;
+            cumulative.push(running);
+        }
+
+        let mut resampled = Vec::with_capacity(self.particles.len());
+        for _ in 0..self.particles.len() {
+            let r = rng.next_scaled(running.max(0.001));
+            let index = cumulative.iter().position(|&c| c >= r).unwrap_or(cumulative.len() - 1);
+            resampled.push(self.particles[index]);
+        }
+        self.particles = resampled;
+
+        let uniform = 1.0 / self.weights.len() as f32;
+        for weight in self.weights.iter_mut() {
+            *weight = uniform;
+        }
+    }
+
+    /// Weighted-mean particle, used as the predator's estimate of the flock's true position.
+    fn estimate(&self) -> Point2<f32> {
+        let mut total = Vector2::zeros();
+        for (particle, weight) in self.particles.iter().zip(self.weights.iter()) {
+            total += particle.coords * *weight;
+        }
+        Point2::from(total)
+    }
+}
+
+/// A predator that chases the flock's (estimated) center of mass, triggering nearby boids' flee
+/// response. `#[repr(C)]` so the host can read predators directly out of wasm memory, same as
+/// `Boid`.
+#[wasm_bindgen]
+#[derive(Clone)]
+#[repr(C)]
+pub struct Predator {
+    position: Point2<f32>,
+    velocity: Vector2<f32>,
+}
+
+impl Predator {
+    fn new(x: f32, y: f32) -> Predator {
+        Predator {
+            position: Point2::new(x, y),
+            velocity: Vector2::x_axis().into_inner(),
+        }
+    }
+
+    /// Steers toward `target` (the particle filter's estimated flock center) and advances position.
+    fn update(&mut self, target: Point2<f32>, dt: f32) {
+        let diff = target - self.position;
+        let diff_mag = diff.magnitude();
+        let acc = if diff_mag > 0.001 {
+            diff / diff_mag * PREDATOR_MAX_ACC
+        } else {
+            Vector2::zeros()
+        };
+        self.velocity = clamp_mag(self.velocity + acc * dt, PREDATOR_MAX_SPEED);
+        self.position += self.velocity * dt;
+    }
+}
+
+/// Uniform grid over boid positions, binned into cells the size of the current
+/// `SimParams::local_range` (`LOCAL_RANGE` by default).
+/// Rebuilt once per `Flock::update` so neighbor queries only need to scan a boid's own cell and
+/// its eight neighbors instead of the whole flock.
+struct Grid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl Grid {
+    fn new(cell_size: f32) -> Grid {
+        Grid {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell_of(&self, position: Point2<f32>) -> (i32, i32) {
+        (
+            (position.x / self.cell_size).floor() as i32,
+            (position.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    fn rebuild(&mut self, members: &[Boid], cell_size: f32) {
+        self.cell_size = cell_size;
+        self.cells.clear();
+        for (i, boid) in members.iter().enumerate() {
+            self.cells.entry(self.cell_of(boid.position)).or_default().push(i);
+        }
+    }
+
+    /// Fills `out` with the indices of every member within `range_sq` of `members[index]`,
+    /// excluding `index` itself.
+    fn query_neighbors(&self, members: &[Boid], index: usize, range_sq: f32, out: &mut Vec<usize>) {
+        out.clear();
+        let member = &members[index];
+        let (cx, cy) = self.cell_of(member.position);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                if let Some(bucket) = self.cells.get(&(cx + dx, cy + dy)) {
+                    for &i in bucket {
+                        if i != index && member.is_close(&members[i], range_sq) {
+                            out.push(i);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A neighbor candidate ordered by squared distance, so a `BinaryHeap<Candidate>` can be used as
+/// a bounded max-heap: the farthest candidate kept is always at the top, ready to be evicted.
+struct Candidate {
+    dist_sq: f32,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.dist_sq == other.dist_sq
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist_sq.partial_cmp(&other.dist_sq).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Narrows `candidates` (already known to be in range) down to at most `params.max_neighbors` of
+/// the closest ones ahead of `member` within its field of view, filling `out`. Uses a bounded
+/// max-heap so the work stays small even when a cell is densely packed.
+fn select_neighbors(
+    member: &Boid,
+    members: &[Boid],
+    candidates: &[usize],
+    params: &SimParams,
+    out: &mut Vec<usize>,
+) {
+    out.clear();
+    let heading_mag = member.velocity.magnitude();
+    let mut heap: BinaryHeap<Candidate> = BinaryHeap::with_capacity(params.max_neighbors as usize + 1);
+
+    for &i in candidates {
+        let diff = members[i].position - member.position;
+        let diff_mag = diff.magnitude();
+        if heading_mag > 0.001 && diff_mag > 0.001 {
+            let cos = diff.dot(&member.velocity) / (diff_mag * heading_mag);
+            if cos < params.view_cos_threshold {
+                continue;
+            }
+        }
+
+        let candidate = Candidate { dist_sq: diff_mag * diff_mag, index: i };
+        if heap.len() < params.max_neighbors as usize {
+            heap.push(candidate);
+        } else if heap.peek().is_some_and(|farthest| candidate.dist_sq < farthest.dist_sq) {
+            heap.pop();
+            heap.push(candidate);
+        }
+    }
+
+    out.extend(heap.into_iter().map(|c| c.index));
+}
+
 #[wasm_bindgen]
 pub struct Flock {
     members: Vec<Boid>,
     /// Determines the boundry of the flock as the box from (0,0) to bounds
     bounds: Vector2<f32>,
-    /// Location of a circle obstacle for the flock to avoid
-    obstacle: Option<Point2<f32>>,
-    /// Buffer for using neighbor calcualtions
-    neighbor_buffer: Vec<Boid>,
+    /// Obstacles for the flock to avoid
+    obstacles: Vec<Obstacle>,
+    /// Blend radius `k` used to smooth-union the obstacles' signed-distance fields
+    obstacle_blend: f32,
+    /// Points (with per-target weight) the flock steers toward
+    attractors: Vec<(Point2<f32>, f32)>,
+    /// Points (with per-target weight) the flock steers away from
+    repellers: Vec<(Point2<f32>, f32)>,
+    /// Predators chasing the flock's estimated center of mass
+    predators: Vec<Predator>,
+    /// Standard deviation of a predator's noisy observation of the flock center
+    predator_noise: f32,
+    /// Per-predator particle filter tracking its belief about the flock's true center
+    particle_filters: Vec<ParticleFilter>,
+    /// Flattened snapshot of every predator's particles, rebuilt each update so the host can
+    /// optionally render the particle cloud
+    particle_cloud: Vec<Point2<f32>>,
+    /// Source of randomness for the particle filters
+    rng: Rng,
+    /// Tunable weights and limits applied to every member each update
+    params: SimParams,
+    /// Spatial hash of member positions, rebuilt once per `update`
+    grid: Grid,
+    /// Buffer of raw in-range candidate indices from the grid, reused for every member each update
+    candidate_buffer: Vec<usize>,
+    /// Buffer of the selected k-nearest, in-view neighbor indices, reused for every member each update
+    neighbor_buffer: Vec<usize>,
 }
 
 #[wasm_bindgen]
@@ -176,7 +745,18 @@ impl Flock {
         Flock {
             members: Vec::new(),
             bounds: Vector2::new(x, y),
-            obstacle: None,
+            obstacles: Vec::new(),
+            obstacle_blend: DEFAULT_OBSTACLE_BLEND,
+            attractors: Vec::new(),
+            repellers: Vec::new(),
+            predators: Vec::new(),
+            predator_noise: DEFAULT_PREDATOR_NOISE,
+            particle_filters: Vec::new(),
+            particle_cloud: Vec::new(),
+            rng: Rng::new(0x1234_5678),
+            params: SimParams::new(),
+            grid: Grid::new(LOCAL_RANGE),
+            candidate_buffer: Vec::new(),
             neighbor_buffer: Vec::new(),
         }
     }
@@ -193,7 +773,18 @@ impl Flock {
         Flock {
             members,
             bounds: Vector2::new(x, y),
-            obstacle: None,
+            obstacles: Vec::new(),
+            obstacle_blend: DEFAULT_OBSTACLE_BLEND,
+            attractors: Vec::new(),
+            repellers: Vec::new(),
+            predators: Vec::new(),
+            predator_noise: DEFAULT_PREDATOR_NOISE,
+            particle_filters: Vec::new(),
+            particle_cloud: Vec::new(),
+            rng: Rng::new(0x1234_5678),
+            params: SimParams::new(),
+            grid: Grid::new(LOCAL_RANGE),
+            candidate_buffer: Vec::with_capacity(10),
             neighbor_buffer: Vec::with_capacity(10),
         }
     }
@@ -206,12 +797,86 @@ impl Flock {
         self.bounds.x = width;
     }
 
-    pub fn set_obstacle(&mut self, x: f32, y: f32) {
-        self.obstacle = Some(Point2::new(x, y));
+    pub fn add_circle_obstacle(&mut self, x: f32, y: f32, radius: f32) {
+        self.obstacles.push(Obstacle::Circle { center: Point2::new(x, y), radius });
+    }
+
+    pub fn add_box_obstacle(&mut self, min_x: f32, min_y: f32, max_x: f32, max_y: f32) {
+        self.obstacles.push(Obstacle::AabbBox {
+            min: Point2::new(min_x, min_y),
+            max: Point2::new(max_x, max_y),
+        });
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.obstacles.clear();
+    }
+
+    pub fn set_obstacle_blend(&mut self, k: f32) {
+        self.obstacle_blend = k;
+    }
+
+    /// Adds an attractor the flock will steer toward, returning its id for later `move_attractor`
+    /// calls.
+    pub fn add_attractor(&mut self, x: f32, y: f32, weight: f32) -> u32 {
+        self.attractors.push((Point2::new(x, y), weight));
+        (self.attractors.len() - 1) as u32
     }
 
-    pub fn clear_obstacle(&mut self) {
-        self.obstacle = None;
+    /// Adds a repeller the flock will steer away from, returning its id.
+    pub fn add_repeller(&mut self, x: f32, y: f32, weight: f32) -> u32 {
+        self.repellers.push((Point2::new(x, y), weight));
+        (self.repellers.len() - 1) as u32
+    }
+
+    /// Moves an existing attractor (by the id returned from `add_attractor`) to a new point.
+    pub fn move_attractor(&mut self, id: u32, x: f32, y: f32) {
+        if let Some((point, _)) = self.attractors.get_mut(id as usize) {
+            *point = Point2::new(x, y);
+        }
+    }
+
+    /// Clears every attractor and repeller.
+    pub fn clear_targets(&mut self) {
+        self.attractors.clear();
+        self.repellers.clear();
+    }
+
+    /// Adds a predator, seeding its particle filter at its spawn point.
+    pub fn add_predator(&mut self, x: f32, y: f32) {
+        self.predators.push(Predator::new(x, y));
+        self.particle_filters.push(ParticleFilter::new(Point2::new(x, y)));
+    }
+
+    /// Sets the standard deviation of a predator's noisy observation of the flock center.
+    pub fn set_predator_noise(&mut self, noise: f32) {
+        self.predator_noise = noise;
+    }
+
+    pub fn predators_ptr(&self) -> *const Predator {
+        self.predators.as_ptr()
+    }
+
+    pub fn num_predators(&self) -> u32 {
+        self.predators.len() as u32
+    }
+
+    /// Pointer to the flattened particle cloud (every predator's particles, in predator order),
+    /// rebuilt each `update`. Optional: only useful if the host wants to render predator belief.
+    pub fn particles_ptr(&self) -> *const Point2<f32> {
+        self.particle_cloud.as_ptr()
+    }
+
+    pub fn num_particles(&self) -> u32 {
+        self.particle_cloud.len() as u32
+    }
+
+    pub fn params(&self) -> SimParams {
+        self.params
+    }
+
+    pub fn set_params(&mut self, params: SimParams) {
+        self.params = params;
     }
 
     pub fn boids_ptr(&self) -> *const Boid {
@@ -224,9 +889,16 @@ impl Flock {
 
     /// Runs the simulation for one time step.
     pub fn update(&mut self, dt: f32) {
+        self.rebuild_grid();
+        self.update_predators(dt);
+
+        let range_sq = self.params.local_range * self.params.local_range;
         for i in 0..self.members.len() {
-            self.set_neighbor_buffer(i);
-            self.members[i].update(&self.neighbor_buffer, dt, self.bounds, self.obstacle);
+            self.grid.query_neighbors(&self.members, i, range_sq, &mut self.candidate_buffer);
+            select_neighbors(&self.members[i], &self.members, &self.candidate_buffer, &self.params, &mut self.neighbor_buffer);
+            let mut member = self.members[i].clone();
+            member.update(&self.members, &self.neighbor_buffer, dt, self.bounds, &self.obstacles, self.obstacle_blend, &self.attractors, &self.repellers, &self.predators, &self.params);
+            self.members[i] = member;
         }
 
         self.update_positions(dt);
@@ -234,22 +906,37 @@ impl Flock {
 
     fn update_positions(&mut self, dt: f32) {
         for boid in self.members.iter_mut() {
-            boid.position = boid.position + dt * boid.velocity;
+            boid.position += dt * boid.velocity;
         }
     }
 }
 
 impl Flock {
-    /// Returns a Vec of all boids near **member**,  but not including member
-    /// Sets into self.neighbor_buffer, this reuses the same allocated space for every update
-    fn set_neighbor_buffer(&mut self, member_index: usize) {
-        let member = self.members[member_index].clone();
-        self.neighbor_buffer.clear();
-
-        for (i, boid) in self.members.iter().enumerate() {
-            if i != member_index && member.is_close(boid) {
-                self.neighbor_buffer.push(boid.clone());
-            }
+    /// Rebuilds the spatial grid from current member positions. Called once per `update` so
+    /// every member's neighbor query that step sees a consistent grid.
+    fn rebuild_grid(&mut self) {
+        self.grid.rebuild(&self.members, self.params.local_range);
+    }
+
+    /// Gives each predator a noisy observation of the true flock center, lets its particle filter
+    /// refine its belief, then steers the predator toward the filter's estimate. Also rebuilds
+    /// `particle_cloud` so the host can render the belief if it wants to.
+    fn update_predators(&mut self, dt: f32) {
+        if self.predators.is_empty() {
+            return;
+        }
+
+        let true_center = flock_center(&self.members)
+            .unwrap_or_else(|| Point2::new(self.bounds.x / 2.0, self.bounds.y / 2.0));
+
+        self.particle_cloud.clear();
+        for (predator, filter) in self.predators.iter_mut().zip(self.particle_filters.iter_mut()) {
+            let noise = self.predator_noise;
+            let observation = true_center
+                + Vector2::new(self.rng.next_signed(), self.rng.next_signed()) * noise;
+            let estimate = filter.update(observation, noise, &mut self.rng);
+            predator.update(estimate, dt);
+            self.particle_cloud.extend_from_slice(&filter.particles);
         }
     }
 }
@@ -284,4 +971,148 @@ mod tests {
             assert_eq!(vals[3], 0.0, "vals[3] value should be 0.0");
         }
     }
+
+    #[test]
+    fn test_grid_finds_close_neighbor() {
+        let members = vec![Boid::new(0.0, 0.0), Boid::new(10.0, 0.0), Boid::new(1000.0, 1000.0)];
+        let mut grid = Grid::new(LOCAL_RANGE);
+        grid.rebuild(&members, LOCAL_RANGE);
+
+        let mut out = Vec::new();
+        grid.query_neighbors(&members, 0, LOCAL_RANGE * LOCAL_RANGE, &mut out);
+
+        assert_eq!(out, vec![1]);
+    }
+
+    #[test]
+    fn test_circle_sdf_sign() {
+        let circle = Obstacle::Circle { center: Point2::new(0.0, 0.0), radius: 50.0 };
+        assert!(circle.sdf(Point2::new(0.0, 0.0)) < 0.0);
+        assert!((circle.sdf(Point2::new(50.0, 0.0))).abs() < 0.001);
+        assert!(circle.sdf(Point2::new(100.0, 0.0)) > 0.0);
+    }
+
+    #[test]
+    fn test_box_sdf_sign() {
+        let b = Obstacle::AabbBox { min: Point2::new(-50.0, -50.0), max: Point2::new(50.0, 50.0) };
+        assert!(b.sdf(Point2::new(0.0, 0.0)) < 0.0);
+        assert!((b.sdf(Point2::new(50.0, 0.0))).abs() < 0.001);
+        assert!(b.sdf(Point2::new(100.0, 0.0)) > 0.0);
+    }
+
+    #[test]
+    fn test_smin_never_exceeds_min() {
+        assert!(smin(3.0, 7.0, 5.0) <= 3.0_f32.min(7.0));
+        assert!(smin(-2.0, 10.0, 2.0) <= (-2.0_f32).min(10.0));
+    }
+
+    #[test]
+    fn test_smin_nonpositive_k_is_plain_min() {
+        assert_eq!(smin(3.0, 7.0, 0.0), 3.0);
+        assert_eq!(smin(3.0, 7.0, -1.0), 3.0);
+    }
+
+    #[test]
+    fn test_avoid_obstacles_pushes_away_from_circle() {
+        let boid = Boid::new(60.0, 0.0);
+        let obstacles = vec![Obstacle::Circle { center: Point2::new(0.0, 0.0), radius: 50.0 }];
+        let params = SimParams::new();
+
+        let push = boid.avoid_obstacles(&obstacles, DEFAULT_OBSTACLE_BLEND, &params);
+
+        assert!(push.x > 0.0);
+    }
+
+    #[test]
+    fn test_particle_filter_converges_to_stationary_observation() {
+        let observation = Point2::new(500.0, -200.0);
+        let mut filter = ParticleFilter::new(Point2::new(0.0, 0.0));
+        let mut rng = Rng::new(42);
+
+        let first_estimate = filter.update(observation, DEFAULT_PREDATOR_NOISE, &mut rng);
+        let mut last_estimate = first_estimate;
+        for _ in 0..30 {
+            last_estimate = filter.update(observation, DEFAULT_PREDATOR_NOISE, &mut rng);
+        }
+
+        let first_err = (first_estimate - observation).magnitude();
+        let last_err = (last_estimate - observation).magnitude();
+        assert!(last_err < first_err);
+    }
+
+    #[test]
+    fn test_particle_filter_degenerate_weights_fall_back_to_uniform() {
+        // Particles all start far from an observation with a tiny noise, so every Gaussian
+        // likelihood underflows to exactly 0.0 and `total_weight` is 0.
+        let mut filter = ParticleFilter::new(Point2::new(0.0, 0.0));
+        let mut rng = Rng::new(7);
+
+        let estimate = filter.update(Point2::new(1.0e6, 1.0e6), 0.001, &mut rng);
+
+        assert!(!estimate.x.is_nan());
+        assert!(!estimate.y.is_nan());
+        let weight_sum: f32 = filter.weights.iter().sum();
+        assert!((weight_sum - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_particle_filter_resample_preserves_particle_count() {
+        let mut filter = ParticleFilter::new(Point2::new(0.0, 0.0));
+        let mut rng = Rng::new(99);
+
+        filter.update(Point2::new(10.0, 10.0), DEFAULT_PREDATOR_NOISE, &mut rng);
+
+        assert_eq!(filter.particles.len(), PARTICLE_COUNT);
+        assert_eq!(filter.weights.len(), PARTICLE_COUNT);
+    }
+
+    #[test]
+    fn test_select_neighbors_caps_at_max_and_keeps_closest() {
+        let member = Boid::new(0.0, 0.0);
+        let members = vec![
+            Boid::new(10.0, 0.0),
+            Boid::new(20.0, 0.0),
+            Boid::new(30.0, 0.0),
+            Boid::new(40.0, 0.0),
+        ];
+        let candidates = vec![0, 1, 2, 3];
+        let mut params = SimParams::new();
+        params.set_max_neighbors(2);
+
+        let mut out = Vec::new();
+        select_neighbors(&member, &members, &candidates, &params, &mut out);
+
+        out.sort();
+        assert_eq!(out, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_select_neighbors_rejects_candidate_behind() {
+        // `member` faces +x (the default heading from `Boid::new`); a candidate directly behind
+        // it on the -x axis should be excluded once the field of view is narrowed.
+        let member = Boid::new(0.0, 0.0);
+        let members = vec![Boid::new(-10.0, 0.0)];
+        let candidates = vec![0];
+        let mut params = SimParams::new();
+        params.set_view_angle(std::f32::consts::FRAC_PI_2);
+
+        let mut out = Vec::new();
+        select_neighbors(&member, &members, &candidates, &params, &mut out);
+
+        assert_eq!(out, Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_select_neighbors_zero_max_is_empty() {
+        let member = Boid::new(0.0, 0.0);
+        let members = vec![Boid::new(10.0, 0.0)];
+        let candidates = vec![0];
+        let mut params = SimParams::new();
+        params.set_max_neighbors(0);
+
+        let mut out = Vec::new();
+        select_neighbors(&member, &members, &candidates, &params, &mut out);
+
+        assert_eq!(out, Vec::<usize>::new());
+    }
 }